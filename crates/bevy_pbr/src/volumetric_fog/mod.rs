@@ -0,0 +1,135 @@
+mod raymarch_pipeline;
+mod render;
+mod temporal_pipeline;
+mod upsampling_pipeline;
+
+pub use render::{VolumetricFogDepthTexture, VolumetricFogTexture, FOG_TEXTURE_FORMAT};
+pub use upsampling_pipeline::{FogResolutionScaleBucket, FogUpsamplingPipelineKeys, UpsamplingUniforms};
+
+use bevy_app::{App, Plugin};
+use bevy_core_pipeline::core_3d::graph::{Core3d, Node3d};
+use bevy_ecs::prelude::Component;
+use bevy_render::{
+    extract_component::{ExtractComponentPlugin, UniformComponentPlugin},
+    render_graph::RenderGraphExt,
+    render_resource::SpecializedRenderPipelines,
+    Render, RenderApp, RenderSet,
+};
+
+use raymarch_pipeline::{
+    add_raymarch_graph_node, prepare_raymarch_bind_groups, prepare_raymarch_pipeline,
+    RaymarchPipeline, VolumetricFogRaymarchLabel,
+};
+use temporal_pipeline::{
+    add_temporal_resolve_graph_node, prepare_temporal_resolve_bind_groups,
+    prepare_temporal_resolve_pipeline, prepare_volumetric_fog_history_textures,
+    update_previous_view_projection_history, PreviousVolumetricFogViewProjections,
+    TemporalResolvePipeline, TemporalUniforms, VolumetricFogTemporalResolveLabel,
+};
+use upsampling_pipeline::{
+    add_upsampling_graph_node, prepare_upsampling_bind_groups, prepare_upsampling_pipeline,
+    UpsamplingPipeline, VolumetricFogUpsamplingLabel,
+};
+
+/// Adds fog simulating the scattering of light through a volume, such as
+/// fog, mist, or smoke.
+///
+/// Add this component to a camera to enable volumetric fog for that camera.
+#[derive(Component, Clone, Copy)]
+pub struct VolumetricFog {
+    /// The fraction of the view's physical resolution that fog is
+    /// raymarched and accumulated at, before being bilaterally upsampled
+    /// back to full resolution. Lower values trade fog detail for
+    /// performance; values below `0.5` switch upsampling to a wider
+    /// reconstruction filter to hide blockiness.
+    pub resolution_scale: f32,
+    /// Blend factor between the freshly raymarched result and its temporal
+    /// history. Values closer to `1.0` de-noise more aggressively at the
+    /// cost of more lag behind fast motion.
+    pub temporal_reprojection_alpha: f32,
+    /// Depth-difference threshold, in the depth buffer's normalized device
+    /// depth units, beyond which
+    /// a reprojected temporal history sample is treated as disoccluded and
+    /// discarded rather than blended in.
+    pub disocclusion_depth_threshold: f32,
+}
+
+impl Default for VolumetricFog {
+    fn default() -> Self {
+        Self {
+            resolution_scale: 0.5,
+            temporal_reprojection_alpha: 0.9,
+            disocclusion_depth_threshold: 0.05,
+        }
+    }
+}
+
+/// Adds support for [`VolumetricFog`].
+pub struct VolumetricFogPlugin;
+
+impl Plugin for VolumetricFogPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            ExtractComponentPlugin::<VolumetricFog>::default(),
+            UniformComponentPlugin::<UpsamplingUniforms>::default(),
+            UniformComponentPlugin::<TemporalUniforms>::default(),
+        ));
+
+        add_raymarch_graph_node(app);
+        add_temporal_resolve_graph_node(app);
+        add_upsampling_graph_node(app);
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        // Raymarch writes the fog/depth textures, temporal resolve blends
+        // them with history, upsampling blends the result into the view
+        // target, and bloom reads the view target in turn.
+        render_app.add_render_graph_edges(
+            Core3d,
+            (
+                Node3d::MainOpaquePass,
+                VolumetricFogRaymarchLabel,
+                VolumetricFogTemporalResolveLabel,
+                VolumetricFogUpsamplingLabel,
+                Node3d::Bloom,
+            ),
+        );
+
+        render_app
+            .init_resource::<PreviousVolumetricFogViewProjections>()
+            .add_systems(
+                Render,
+                (
+                    update_previous_view_projection_history.in_set(RenderSet::Prepare),
+                    (
+                        render::prepare_volumetric_fog_textures,
+                        prepare_volumetric_fog_history_textures,
+                    )
+                        .chain()
+                        .in_set(RenderSet::PrepareResources),
+                    prepare_upsampling_pipeline.in_set(RenderSet::Prepare),
+                    prepare_temporal_resolve_pipeline.in_set(RenderSet::Prepare),
+                    prepare_raymarch_pipeline.in_set(RenderSet::Prepare),
+                    prepare_upsampling_bind_groups.in_set(RenderSet::PrepareBindGroups),
+                    prepare_temporal_resolve_bind_groups.in_set(RenderSet::PrepareBindGroups),
+                    prepare_raymarch_bind_groups.in_set(RenderSet::PrepareBindGroups),
+                ),
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app
+            .init_resource::<UpsamplingPipeline>()
+            .init_resource::<SpecializedRenderPipelines<UpsamplingPipeline>>()
+            .init_resource::<TemporalResolvePipeline>()
+            .init_resource::<SpecializedRenderPipelines<TemporalResolvePipeline>>()
+            .init_resource::<RaymarchPipeline>()
+            .init_resource::<SpecializedRenderPipelines<RaymarchPipeline>>();
+    }
+}
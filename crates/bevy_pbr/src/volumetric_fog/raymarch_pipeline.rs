@@ -0,0 +1,217 @@
+use bevy_app::App;
+use bevy_asset::Handle;
+use bevy_core_pipeline::{core_3d::graph::Core3d, fullscreen_vertex_shader::fullscreen_shader_vertex_state};
+use bevy_ecs::{
+    prelude::{Component, Entity},
+    query::{QueryItem, With},
+    system::{Commands, Query, Res, ResMut, Resource},
+    world::{FromWorld, World},
+};
+use bevy_render::{
+    extract_component::ComponentUniforms,
+    render_graph::{
+        NodeRunError, RenderGraphContext, RenderGraphExt, RenderLabel, ViewNode, ViewNodeRunner,
+    },
+    render_resource::{
+        binding_types::{texture_depth_2d, uniform_buffer},
+        *,
+    },
+    renderer::{RenderContext, RenderDevice},
+    view::ViewDepthTexture,
+    RenderApp,
+};
+
+use super::{
+    render::{VolumetricFogDepthTexture, VolumetricFogTexture, FOG_DEPTH_TEXTURE_FORMAT, FOG_TEXTURE_FORMAT},
+    temporal_pipeline::TemporalUniforms,
+    VolumetricFog,
+};
+
+#[derive(Component)]
+pub struct RaymarchBindGroup {
+    pub bind_group: BindGroup,
+}
+
+#[derive(Component)]
+pub struct RaymarchPipelineId(pub CachedRenderPipelineId);
+
+#[derive(Resource)]
+pub struct RaymarchPipeline {
+    pub bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for RaymarchPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "fog_raymarch_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    // Full-resolution view depth, sampled (offset by this
+                    // frame's jitter) to determine how far the raymarch
+                    // travels before hitting a surface.
+                    texture_depth_2d(),
+                    // TemporalUniforms, for this frame's raymarch_jitter.
+                    uniform_buffer::<TemporalUniforms>(true),
+                ),
+            ),
+        );
+
+        RaymarchPipeline { bind_group_layout }
+    }
+}
+
+pub const RAYMARCH_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(0x2a9f6a0c8a9a4a2ea9d6f0e1c6c6f6a1);
+
+impl SpecializedRenderPipeline for RaymarchPipeline {
+    type Key = ();
+
+    fn specialize(&self, _key: Self::Key) -> RenderPipelineDescriptor {
+        RenderPipelineDescriptor {
+            label: Some("fog_raymarch_pipeline".into()),
+            layout: vec![self.bind_group_layout.clone()],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: RAYMARCH_SHADER_HANDLE,
+                shader_defs: vec![],
+                entry_point: "raymarch".into(),
+                targets: vec![
+                    // Low-res fog color.
+                    Some(ColorTargetState {
+                        format: FOG_TEXTURE_FORMAT,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    }),
+                    // Low-res depth, consumed by bilateral upsampling.
+                    Some(ColorTargetState {
+                        format: FOG_DEPTH_TEXTURE_FORMAT,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    }),
+                ],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            push_constant_ranges: Vec::new(),
+            zero_initialize_workgroup_memory: false,
+        }
+    }
+}
+
+pub fn prepare_raymarch_pipeline(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<RaymarchPipeline>>,
+    pipeline: Res<RaymarchPipeline>,
+    views: Query<(Entity, &VolumetricFog)>,
+) {
+    for (entity, _fog) in &views {
+        let pipeline_id = pipelines.specialize(&pipeline_cache, &pipeline, ());
+        commands
+            .entity(entity)
+            .insert(RaymarchPipelineId(pipeline_id));
+    }
+}
+
+pub fn prepare_raymarch_bind_groups(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    pipeline: Res<RaymarchPipeline>,
+    uniforms: Res<ComponentUniforms<TemporalUniforms>>,
+    views: Query<(Entity, &ViewDepthTexture), With<VolumetricFog>>,
+) {
+    let Some(uniforms_binding) = uniforms.binding() else {
+        return;
+    };
+
+    for (entity, view_depth_texture) in &views {
+        let bind_group = render_device.create_bind_group(
+            "fog_raymarch_bind_group",
+            &pipeline.bind_group_layout,
+            &BindGroupEntries::sequential((view_depth_texture.view(), uniforms_binding.clone())),
+        );
+
+        commands
+            .entity(entity)
+            .insert(RaymarchBindGroup { bind_group });
+    }
+}
+
+#[derive(RenderLabel, Debug, Clone, Hash, PartialEq, Eq)]
+pub struct VolumetricFogRaymarchLabel;
+
+/// Renders the volumetric fog raymarch pass: samples the full-resolution
+/// view depth (offset by this frame's jitter) and writes a low-resolution
+/// fog color and depth, which temporal resolve and then bilateral
+/// upsampling consume in turn.
+#[derive(Default)]
+pub struct RaymarchNode;
+
+impl ViewNode for RaymarchNode {
+    type ViewQuery = (
+        &'static RaymarchBindGroup,
+        &'static RaymarchPipelineId,
+        &'static VolumetricFogTexture,
+        &'static VolumetricFogDepthTexture,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (bind_group, pipeline_id, fog_texture, fog_depth_texture): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let Some(pipeline) = pipeline_cache.get_render_pipeline(pipeline_id.0) else {
+            return Ok(());
+        };
+
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("fog_raymarch_pass"),
+            color_attachments: &[
+                Some(RenderPassColorAttachment {
+                    view: &fog_texture.view(),
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Default::default()),
+                        store: StoreOp::Store,
+                    },
+                }),
+                Some(RenderPassColorAttachment {
+                    view: &fog_depth_texture.view(),
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Default::default()),
+                        store: StoreOp::Store,
+                    },
+                }),
+            ],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_render_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
+
+/// Registers the raymarch pass's render-graph node. Edge wiring (ordering it
+/// before temporal resolve) happens once, alongside the other volumetric fog
+/// nodes, in `VolumetricFogPlugin::build`.
+pub fn add_raymarch_graph_node(app: &mut App) {
+    let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+        return;
+    };
+
+    render_app
+        .add_render_graph_node::<ViewNodeRunner<RaymarchNode>>(Core3d, VolumetricFogRaymarchLabel);
+}
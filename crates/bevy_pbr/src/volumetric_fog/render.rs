@@ -0,0 +1,114 @@
+use bevy_ecs::{
+    prelude::{Component, Entity},
+    system::{Commands, Query, Res, ResMut},
+};
+use bevy_render::{
+    camera::ExtractedCamera,
+    render_resource::{
+        Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
+    },
+    renderer::RenderDevice,
+    texture::{CachedTexture, TextureCache},
+};
+
+use super::VolumetricFog;
+
+pub const FOG_TEXTURE_FORMAT: TextureFormat = TextureFormat::Rg11b10Float;
+
+/// Depth is stored separately from the fog texture's alpha channel so that
+/// it survives the fog texture's additive blending into the view target.
+pub const FOG_DEPTH_TEXTURE_FORMAT: TextureFormat = TextureFormat::R32Float;
+
+/// The low-resolution fog accumulated by raymarching, sized according to
+/// [`VolumetricFog::resolution_scale`].
+#[derive(Component)]
+pub struct VolumetricFogTexture(CachedTexture);
+
+impl VolumetricFogTexture {
+    pub fn view(&self) -> TextureView {
+        self.0.default_view.clone()
+    }
+
+    pub fn size(&self) -> Extent3d {
+        self.0.texture.size()
+    }
+}
+
+/// The low-resolution depth produced by the raymarch pass alongside
+/// [`VolumetricFogTexture`], at the same resolution. Consumed by bilateral
+/// upsampling to avoid bleeding fog across depth discontinuities.
+#[derive(Component)]
+pub struct VolumetricFogDepthTexture(CachedTexture);
+
+impl VolumetricFogDepthTexture {
+    pub fn view(&self) -> TextureView {
+        self.0.default_view.clone()
+    }
+}
+
+pub(crate) fn low_res_extent(
+    physical_viewport_size: bevy_math::UVec2,
+    resolution_scale: f32,
+) -> Extent3d {
+    let resolution_scale = resolution_scale.clamp(0.05, 1.0);
+    let size = (physical_viewport_size.as_vec2() * resolution_scale)
+        .max(bevy_math::Vec2::ONE)
+        .as_uvec2();
+
+    Extent3d {
+        width: size.x,
+        height: size.y,
+        depth_or_array_layers: 1,
+    }
+}
+
+/// Allocates the fog texture and its companion low-resolution depth texture
+/// that the raymarch pass renders into, both sized by
+/// [`VolumetricFog::resolution_scale`].
+pub fn prepare_volumetric_fog_textures(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    mut texture_cache: ResMut<TextureCache>,
+    views: Query<(Entity, &ExtractedCamera, &VolumetricFog)>,
+) {
+    for (entity, camera, fog) in &views {
+        let Some(physical_viewport_size) = camera.physical_viewport_size else {
+            continue;
+        };
+
+        let size = low_res_extent(physical_viewport_size, fog.resolution_scale);
+
+        let fog_texture = texture_cache.get(
+            &render_device,
+            TextureDescriptor {
+                label: Some("volumetric_fog_texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: FOG_TEXTURE_FORMAT,
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            },
+        );
+
+        let fog_depth_texture = texture_cache.get(
+            &render_device,
+            TextureDescriptor {
+                label: Some("volumetric_fog_depth_texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: FOG_DEPTH_TEXTURE_FORMAT,
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            },
+        );
+
+        commands.entity(entity).insert((
+            VolumetricFogTexture(fog_texture),
+            VolumetricFogDepthTexture(fog_depth_texture),
+        ));
+    }
+}
@@ -0,0 +1,423 @@
+use bevy_app::App;
+use bevy_asset::Handle;
+use bevy_core::FrameCount;
+use bevy_core_pipeline::{
+    core_3d::graph::Core3d,
+    fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+};
+use bevy_ecs::{
+    entity::EntityHashMap,
+    prelude::{Component, Entity},
+    query::QueryItem,
+    system::{Commands, Query, Res, ResMut, Resource},
+    world::{FromWorld, World},
+};
+use bevy_math::{Mat4, Vec2};
+use bevy_render::{
+    extract_component::ComponentUniforms,
+    render_graph::{NodeRunError, RenderGraphContext, RenderGraphExt, RenderLabel, ViewNode, ViewNodeRunner},
+    render_resource::{
+        binding_types::{sampler, texture_2d, uniform_buffer},
+        *,
+    },
+    renderer::{RenderContext, RenderDevice},
+    texture::{CachedTexture, TextureCache},
+    view::ExtractedView,
+    RenderApp,
+};
+
+use super::{
+    render::{VolumetricFogDepthTexture, VolumetricFogTexture, FOG_TEXTURE_FORMAT},
+    VolumetricFog,
+};
+
+/// `R16Float`-packed depth written alongside the resolved fog color, so that
+/// next frame's resolve pass can tell whether the surface it reprojected to
+/// is the same one that was visible when the history was written.
+const HISTORY_DEPTH_TEXTURE_FORMAT: TextureFormat = TextureFormat::R16Float;
+
+/// Per-view uniforms consumed by the temporal resolve pass.
+#[derive(Component, ShaderType, Clone)]
+pub struct TemporalUniforms {
+    /// The view-projection matrix from the previous frame, used to
+    /// reproject the current froxel result into history space.
+    pub previous_view_proj: Mat4,
+    /// The current frame's inverse view-projection matrix, used to
+    /// unproject a screen-space pixel and its depth back to world space
+    /// before reprojecting it with `previous_view_proj`.
+    pub world_from_clip: Mat4,
+    /// Blend factor between the freshly raymarched result and history.
+    /// `0.0` disables accumulation entirely; values close to `1.0` favor
+    /// history heavily and de-noise more aggressively at the cost of lag.
+    pub alpha: f32,
+    /// Depth-difference threshold, in the depth buffer's normalized device
+    /// depth units, beyond which a reprojected history sample is rejected
+    /// as a disocclusion.
+    pub depth_rejection_threshold: f32,
+    /// This frame's jitter, in `[0, 1)^2`, taken from an R2 low-discrepancy
+    /// sequence indexed by frame count. The raymarch pass maps it to a
+    /// whole-texel offset of `-1`, `0` or `1` per axis of the position at
+    /// which it samples the full-resolution view depth, so that successive
+    /// frames sample different depths, giving the temporal resolve pass new
+    /// information to accumulate. It does not offset a ray's start position.
+    pub raymarch_jitter: Vec2,
+}
+
+/// `1/phi2` and `1/phi2^2`, where `phi2` is the positive root of
+/// `x^3 = x + 1`: the two irrational constants that generate the 2D R2
+/// low-discrepancy sequence.
+const R2_ALPHA: Vec2 = Vec2::new(0.754_877_7, 0.569_840_3);
+
+/// Returns the `frame_count`-th term of the R2 sequence, in `[0, 1)^2`.
+///
+/// Compared to a Halton sequence this is cheaper to evaluate per-frame (no
+/// per-digit loop) and has slightly better 2D discrepancy, which is why
+/// it's used to jitter the position at which the volumetric fog raymarch
+/// pass samples the view depth.
+pub fn r2_jitter(frame_count: u32) -> Vec2 {
+    (Vec2::splat(0.5) + R2_ALPHA * frame_count as f32).fract()
+}
+
+/// Two ping-pong pairs of low-resolution textures used to accumulate fog
+/// samples across frames: the resolved color, and the depth that was
+/// current when that color was written (used to detect disocclusions).
+#[derive(Component)]
+pub struct VolumetricFogHistoryTextures {
+    color: [CachedTexture; 2],
+    depth: [CachedTexture; 2],
+}
+
+impl VolumetricFogHistoryTextures {
+    /// The color texture holding the previous frame's resolved result.
+    pub fn read_color(&self, frame_count: u32) -> &CachedTexture {
+        &self.color[(frame_count % 2) as usize]
+    }
+
+    /// The color texture this frame's resolved result should be written into.
+    pub fn write_color(&self, frame_count: u32) -> &CachedTexture {
+        &self.color[((frame_count + 1) % 2) as usize]
+    }
+
+    /// The depth texture matching [`Self::read_color`].
+    pub fn read_depth(&self, frame_count: u32) -> &CachedTexture {
+        &self.depth[(frame_count % 2) as usize]
+    }
+
+    /// The depth texture matching [`Self::write_color`].
+    pub fn write_depth(&self, frame_count: u32) -> &CachedTexture {
+        &self.depth[((frame_count + 1) % 2) as usize]
+    }
+}
+
+pub fn prepare_volumetric_fog_history_textures(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    mut texture_cache: ResMut<TextureCache>,
+    views: Query<(Entity, &VolumetricFogTexture)>,
+) {
+    for (entity, volumetric_fog_texture) in &views {
+        let size = volumetric_fog_texture.size();
+
+        let mut history_texture = |label: &'static str, format: TextureFormat| {
+            texture_cache.get(
+                &render_device,
+                TextureDescriptor {
+                    label: Some(label),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format,
+                    usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
+                    view_formats: &[],
+                },
+            )
+        };
+
+        commands
+            .entity(entity)
+            .insert(VolumetricFogHistoryTextures {
+                color: [
+                    history_texture("volumetric_fog_history_color_texture", FOG_TEXTURE_FORMAT),
+                    history_texture("volumetric_fog_history_color_texture", FOG_TEXTURE_FORMAT),
+                ],
+                depth: [
+                    history_texture(
+                        "volumetric_fog_history_depth_texture",
+                        HISTORY_DEPTH_TEXTURE_FORMAT,
+                    ),
+                    history_texture(
+                        "volumetric_fog_history_depth_texture",
+                        HISTORY_DEPTH_TEXTURE_FORMAT,
+                    ),
+                ],
+            });
+    }
+}
+
+#[derive(Component)]
+pub struct TemporalResolveBindGroup {
+    pub bind_group: BindGroup,
+}
+
+#[derive(Component)]
+pub struct TemporalResolvePipelineId(pub CachedRenderPipelineId);
+
+#[derive(Resource)]
+pub struct TemporalResolvePipeline {
+    pub bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for TemporalResolvePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "fog_temporal_resolve_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    // Current frame's raymarched fog texture
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    // Current frame's low-res depth texture. Not a real
+                    // depth-aspect texture (it's `FOG_DEPTH_TEXTURE_FORMAT`,
+                    // written as an ordinary color attachment by the raymarch
+                    // pass), so it's bound the same way as the history depth
+                    // texture below rather than as `texture_depth_2d`.
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    // Resolved color from the previous frame
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    // Depth that was current when the history color was written
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    // Sampler shared between the fog and history textures
+                    sampler(SamplerBindingType::Filtering),
+                    // TemporalUniforms
+                    uniform_buffer::<TemporalUniforms>(true),
+                ),
+            ),
+        );
+
+        TemporalResolvePipeline { bind_group_layout }
+    }
+}
+
+pub const TEMPORAL_RESOLVE_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(0x8f6a7508e1d74d06b8b9f6a6a6fa9e3a);
+
+impl SpecializedRenderPipeline for TemporalResolvePipeline {
+    type Key = ();
+
+    fn specialize(&self, _key: Self::Key) -> RenderPipelineDescriptor {
+        RenderPipelineDescriptor {
+            label: Some("fog_temporal_resolve_pipeline".into()),
+            layout: vec![self.bind_group_layout.clone()],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: TEMPORAL_RESOLVE_SHADER_HANDLE,
+                shader_defs: vec![],
+                entry_point: "resolve".into(),
+                targets: vec![
+                    // Resolved fog color, fed into bilateral upsampling.
+                    Some(ColorTargetState {
+                        format: FOG_TEXTURE_FORMAT,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    }),
+                    // This frame's depth, stored so next frame's resolve can
+                    // detect disocclusions at the reprojected location.
+                    Some(ColorTargetState {
+                        format: HISTORY_DEPTH_TEXTURE_FORMAT,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    }),
+                ],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            push_constant_ranges: Vec::new(),
+            zero_initialize_workgroup_memory: false,
+        }
+    }
+}
+
+pub fn prepare_temporal_resolve_pipeline(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<TemporalResolvePipeline>>,
+    pipeline: Res<TemporalResolvePipeline>,
+    views: Query<(Entity, &VolumetricFog)>,
+) {
+    for (entity, _fog) in &views {
+        let pipeline_id = pipelines.specialize(&pipeline_cache, &pipeline, ());
+        commands
+            .entity(entity)
+            .insert(TemporalResolvePipelineId(pipeline_id));
+    }
+}
+
+pub fn prepare_temporal_resolve_bind_groups(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    pipeline: Res<TemporalResolvePipeline>,
+    uniforms: Res<ComponentUniforms<TemporalUniforms>>,
+    views: Query<(
+        Entity,
+        &VolumetricFogTexture,
+        &VolumetricFogDepthTexture,
+        &VolumetricFogHistoryTextures,
+    )>,
+    frame_count: Res<FrameCount>,
+) {
+    let Some(uniforms_binding) = uniforms.binding() else {
+        return;
+    };
+
+    let sampler = render_device.create_sampler(&SamplerDescriptor {
+        min_filter: FilterMode::Linear,
+        mag_filter: FilterMode::Linear,
+        address_mode_u: AddressMode::ClampToEdge,
+        address_mode_v: AddressMode::ClampToEdge,
+        ..Default::default()
+    });
+
+    for (entity, fog_texture, fog_depth_texture, history_textures) in &views {
+        let bind_group = render_device.create_bind_group(
+            "fog_temporal_resolve_bind_group",
+            &pipeline.bind_group_layout,
+            &BindGroupEntries::sequential((
+                &fog_texture.view(),
+                &fog_depth_texture.view(),
+                &history_textures.read_color(frame_count.0).default_view,
+                &history_textures.read_depth(frame_count.0).default_view,
+                &sampler,
+                uniforms_binding.clone(),
+            )),
+        );
+
+        commands
+            .entity(entity)
+            .insert(TemporalResolveBindGroup { bind_group });
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct PreviousVolumetricFogViewProjections(EntityHashMap<Mat4>);
+
+/// Fills in [`TemporalUniforms::previous_view_proj`] and
+/// [`TemporalUniforms::world_from_clip`] from this view's current matrices,
+/// and sets this frame's raymarch jitter. `previous_view_proj` falls back to
+/// the current frame's matrix (disabling reprojection) the first time a
+/// view is seen.
+pub fn update_previous_view_projection_history(
+    frame_count: Res<FrameCount>,
+    mut history: ResMut<PreviousVolumetricFogViewProjections>,
+    mut views: Query<(Entity, &mut TemporalUniforms, &ExtractedView)>,
+) {
+    for (entity, mut uniforms, view) in &mut views {
+        let world_from_view = view.world_from_view.compute_matrix();
+        let clip_from_world = view.clip_from_view * world_from_view.inverse();
+        let world_from_clip = world_from_view * view.clip_from_view.inverse();
+
+        uniforms.previous_view_proj = history
+            .0
+            .get(&entity)
+            .copied()
+            .unwrap_or(clip_from_world);
+        uniforms.world_from_clip = world_from_clip;
+        uniforms.raymarch_jitter = r2_jitter(frame_count.0);
+
+        history.0.insert(entity, clip_from_world);
+    }
+}
+
+impl VolumetricFog {
+    pub(crate) fn extract_temporal_uniforms(&self) -> TemporalUniforms {
+        TemporalUniforms {
+            // Filled in by `update_previous_view_projection_history` once
+            // this frame's view has been extracted.
+            previous_view_proj: Mat4::IDENTITY,
+            world_from_clip: Mat4::IDENTITY,
+            alpha: self.temporal_reprojection_alpha,
+            depth_rejection_threshold: self.disocclusion_depth_threshold,
+            raymarch_jitter: Vec2::ZERO,
+        }
+    }
+}
+
+#[derive(RenderLabel, Debug, Clone, Hash, PartialEq, Eq)]
+pub struct VolumetricFogTemporalResolveLabel;
+
+/// Renders the temporal resolve pass: blends the current frame's raymarched
+/// fog with its reprojected history and writes the result (plus this
+/// frame's depth) into the history textures that bilateral upsampling and
+/// next frame's resolve will read from.
+#[derive(Default)]
+pub struct TemporalResolveNode;
+
+impl ViewNode for TemporalResolveNode {
+    type ViewQuery = (
+        &'static TemporalResolveBindGroup,
+        &'static TemporalResolvePipelineId,
+        &'static VolumetricFogHistoryTextures,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (bind_group, pipeline_id, history_textures): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let Some(pipeline) = pipeline_cache.get_render_pipeline(pipeline_id.0) else {
+            return Ok(());
+        };
+        let frame_count = world.resource::<FrameCount>();
+
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("fog_temporal_resolve_pass"),
+            color_attachments: &[
+                Some(RenderPassColorAttachment {
+                    view: &history_textures.write_color(frame_count.0).default_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Default::default()),
+                        store: StoreOp::Store,
+                    },
+                }),
+                Some(RenderPassColorAttachment {
+                    view: &history_textures.write_depth(frame_count.0).default_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Default::default()),
+                        store: StoreOp::Store,
+                    },
+                }),
+            ],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_render_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
+
+/// Registers the temporal resolve pass's render-graph node. Edge wiring
+/// (ordering it after the raymarch pass and before bloom) happens once,
+/// alongside the other volumetric fog nodes, in `VolumetricFogPlugin::build`.
+pub fn add_temporal_resolve_graph_node(app: &mut App) {
+    let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+        return;
+    };
+
+    render_app.add_render_graph_node::<ViewNodeRunner<TemporalResolveNode>>(
+        Core3d,
+        VolumetricFogTemporalResolveLabel,
+    );
+}
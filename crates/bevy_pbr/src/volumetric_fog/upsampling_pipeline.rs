@@ -1,26 +1,33 @@
+use bevy_app::App;
 use bevy_asset::Handle;
-use bevy_core_pipeline::fullscreen_vertex_shader::fullscreen_shader_vertex_state;
+use bevy_core::FrameCount;
+use bevy_core_pipeline::{core_3d::graph::Core3d, fullscreen_vertex_shader::fullscreen_shader_vertex_state};
 use bevy_ecs::{
     prelude::{Component, Entity},
     query::QueryItem,
     system::{Commands, Query, Res, ResMut, Resource},
     world::{FromWorld, World},
 };
-use bevy_math::{AspectRatio, URect};
+use bevy_math::{AspectRatio, URect, Vec2};
 use bevy_render::{
     camera::Camera,
     extract_component::{ComponentUniforms, ExtractComponent},
+    render_graph::{
+        NodeRunError, RenderGraphContext, RenderGraphExt, RenderLabel, ViewNode, ViewNodeRunner,
+    },
     render_resource::{
-        binding_types::{sampler, texture_2d, uniform_buffer},
+        binding_types::{sampler, texture_2d, texture_depth_2d, uniform_buffer},
         *,
     },
-    renderer::RenderDevice,
-    view::ViewTarget,
+    renderer::{RenderContext, RenderDevice},
+    view::{ViewDepthTexture, ViewTarget},
+    RenderApp,
 };
 use bevy_utils::tracing::warn;
 
 use super::{
-    render::{VolumetricFogTexture, FOG_TEXTURE_FORMAT},
+    render::{low_res_extent, VolumetricFogDepthTexture},
+    temporal_pipeline::{TemporalUniforms, VolumetricFogHistoryTextures},
     VolumetricFog,
 };
 
@@ -33,14 +40,28 @@ pub struct UpsamplingBindGroup {
 #[derive(Component, ShaderType, Clone)]
 pub struct UpsamplingUniforms {
     pub aspect: f32,
+    /// The reciprocal of the low-resolution fog texture's size (which
+    /// varies with [`VolumetricFog::resolution_scale`]), used to locate the
+    /// four nearest low-res texels for bilateral upsampling.
+    pub inv_low_res_texel_size: Vec2,
+    /// Standard deviation of the depth-difference range weight used by
+    /// bilateral upsampling. Smaller values preserve silhouettes more
+    /// aggressively at the cost of noisier fog near depth discontinuities.
+    pub depth_sigma: f32,
 }
 
 pub fn prepare_upsampling_bind_groups(
     mut commands: Commands,
     render_device: Res<RenderDevice>,
     upsampling_pipeline: Res<UpsamplingPipeline>,
-    views: Query<(Entity, &VolumetricFogTexture)>,
+    views: Query<(
+        Entity,
+        &VolumetricFogHistoryTextures,
+        &VolumetricFogDepthTexture,
+        &ViewDepthTexture,
+    )>,
     uniforms: Res<ComponentUniforms<UpsamplingUniforms>>,
+    frame_count: Res<FrameCount>,
 ) {
     let sampler = render_device.create_sampler(&SamplerDescriptor {
         min_filter: FilterMode::Linear,
@@ -50,14 +71,18 @@ pub fn prepare_upsampling_bind_groups(
         ..Default::default()
     });
 
-    for (entity, volumetric_fog_texture) in &views {
+    for (entity, history_textures, volumetric_fog_depth_texture, view_depth_texture) in &views {
         let upsampling_bind_group = render_device.create_bind_group(
             "bloom_upsampling_bind_group",
             &upsampling_pipeline.bind_group_layout,
             &BindGroupEntries::sequential((
-                &volumetric_fog_texture.view(),
+                // The temporally-resolved fog that this frame's resolve pass
+                // writes, rather than the raw raymarched fog texture.
+                &history_textures.write_color(frame_count.0).default_view,
                 &sampler,
                 uniforms.binding().unwrap(),
+                view_depth_texture.view(),
+                &volumetric_fog_depth_texture.view(),
             )),
         );
 
@@ -78,8 +103,32 @@ pub struct UpsamplingPipeline {
     pub bind_group_layout: BindGroupLayout,
 }
 
+/// A coarse bucketing of [`VolumetricFog::resolution_scale`] used to pick a
+/// shader variant at specialization time, rather than specializing on the
+/// raw float (which would create a new pipeline per distinct scale value).
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum FogResolutionScaleBucket {
+    /// `resolution_scale` of `0.5` or more: a cheap bilinear tap is enough.
+    Fine,
+    /// `resolution_scale` below `0.5`: reconstruct with a wider tent / Catmull-Rom
+    /// filter to hide the blockiness of a small fog texture.
+    Coarse,
+}
+
+impl FogResolutionScaleBucket {
+    fn from_resolution_scale(resolution_scale: f32) -> Self {
+        if resolution_scale < 0.5 {
+            FogResolutionScaleBucket::Coarse
+        } else {
+            FogResolutionScaleBucket::Fine
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Hash, Clone)]
-pub struct FogUpsamplingPipelineKeys {}
+pub struct FogUpsamplingPipelineKeys {
+    pub scale_bucket: FogResolutionScaleBucket,
+}
 
 impl FromWorld for UpsamplingPipeline {
     fn from_world(world: &mut World) -> Self {
@@ -96,6 +145,15 @@ impl FromWorld for UpsamplingPipeline {
                     sampler(SamplerBindingType::Filtering),
                     // FogUniforms
                     uniform_buffer::<UpsamplingUniforms>(true),
+                    // Full-resolution depth texture, used to weight bilateral
+                    // upsampling taps against the low-res fog depth.
+                    texture_depth_2d(),
+                    // Low-resolution depth texture produced alongside the
+                    // fog. Not a real depth-aspect texture (it's
+                    // `FOG_DEPTH_TEXTURE_FORMAT`, written as an ordinary
+                    // color attachment by the raymarch pass), so it can't be
+                    // bound as `texture_depth_2d`.
+                    texture_2d(TextureSampleType::Float { filterable: true }),
                 ),
             ),
         );
@@ -107,20 +165,32 @@ impl FromWorld for UpsamplingPipeline {
 pub const FOG_UPSCALING_SHADER_HANDLE: Handle<Shader> =
     Handle::weak_from_u128(0x14b0e0d8dbeb82cf729f6cc293554932);
 
+/// Default standard deviation, in the depth buffer's normalized device depth
+/// units, of the bilateral upsampling range weight.
+const DEFAULT_BILATERAL_DEPTH_SIGMA: f32 = 0.25;
+
 impl SpecializedRenderPipeline for UpsamplingPipeline {
     type Key = FogUpsamplingPipelineKeys;
 
     fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let mut shader_defs = vec![];
+        if key.scale_bucket == FogResolutionScaleBucket::Coarse {
+            shader_defs.push("FOG_UPSAMPLE_WIDE_FILTER".into());
+        }
+
         RenderPipelineDescriptor {
             label: Some("fog_upsampling_pipeline".into()),
             layout: vec![self.bind_group_layout.clone()],
             vertex: fullscreen_shader_vertex_state(),
             fragment: Some(FragmentState {
                 shader: FOG_UPSCALING_SHADER_HANDLE,
-                shader_defs: vec![],
+                shader_defs,
                 entry_point: "upsample".into(),
                 targets: vec![Some(ColorTargetState {
-                    format: FOG_TEXTURE_FORMAT,
+                    // This pass blends additively into the view target, not
+                    // the low-res fog texture, so it must target the view
+                    // target's own (HDR) format rather than `FOG_TEXTURE_FORMAT`.
+                    format: ViewTarget::TEXTURE_FORMAT_HDR,
                     blend: Some(BlendState {
                         color: BlendComponent {
                             src_factor: BlendFactor::Constant,
@@ -153,8 +223,15 @@ pub fn prepare_upsampling_pipeline(
     views: Query<(Entity, &VolumetricFog)>,
 ) {
     for (entity, fog) in &views {
-        let pipeline_final_id =
-            pipelines.specialize(&pipeline_cache, &pipeline, FogUpsamplingPipelineKeys {});
+        let pipeline_final_id = pipelines.specialize(
+            &pipeline_cache,
+            &pipeline,
+            FogUpsamplingPipelineKeys {
+                scale_bucket: FogResolutionScaleBucket::from_resolution_scale(
+                    fog.resolution_scale,
+                ),
+            },
+        );
 
         commands.entity(entity).insert(UpsamplingPipelineIds {
             id_final: pipeline_final_id,
@@ -166,7 +243,7 @@ impl ExtractComponent for VolumetricFog {
     type QueryData = (&'static Self, &'static Camera);
 
     type QueryFilter = ();
-    type Out = (Self, UpsamplingUniforms);
+    type Out = (Self, UpsamplingUniforms, TemporalUniforms);
 
     fn extract_component(
         (volumetric_fog, camera): QueryItem<'_, Self::QueryData>,
@@ -181,15 +258,89 @@ impl ExtractComponent for VolumetricFog {
             (Some(URect { min: origin, .. }), Some(size), Some(target_size), true, true)
                 if size.x != 0 && size.y != 0 =>
             {
+                // Use the same integer-truncated size `render::low_res_extent`
+                // allocates the fog texture at, so this uniform's texel grid
+                // doesn't drift a fraction of a texel from the real texture's
+                // for viewport sizes that don't divide evenly by
+                // `resolution_scale`.
+                let low_res_size = low_res_extent(size, volumetric_fog.resolution_scale);
+
                 let uniform = UpsamplingUniforms {
                     aspect: AspectRatio::try_from_pixels(size.x, size.y)
                         .expect("Valid screen size values for Bloom settings")
                         .ratio(),
+                    inv_low_res_texel_size: Vec2::new(
+                        1.0 / low_res_size.width as f32,
+                        1.0 / low_res_size.height as f32,
+                    ),
+                    depth_sigma: DEFAULT_BILATERAL_DEPTH_SIGMA,
                 };
 
-                Some((volumetric_fog.clone(), uniform))
+                let temporal_uniform = volumetric_fog.extract_temporal_uniforms();
+
+                Some((volumetric_fog.clone(), uniform, temporal_uniform))
             }
             _ => None,
         }
     }
 }
+
+#[derive(RenderLabel, Debug, Clone, Hash, PartialEq, Eq)]
+pub struct VolumetricFogUpsamplingLabel;
+
+/// Renders the bilateral upsampling pass: reconstructs the low-resolution,
+/// temporally-resolved fog back to full resolution and blends it additively
+/// into the view target, using the constant-alpha blend state set up in
+/// [`UpsamplingPipeline::specialize`].
+#[derive(Default)]
+pub struct UpsamplingNode;
+
+impl ViewNode for UpsamplingNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static UpsamplingBindGroup,
+        &'static UpsamplingPipelineIds,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (view_target, bind_group, pipeline_ids): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let Some(pipeline) = pipeline_cache.get_render_pipeline(pipeline_ids.id_final) else {
+            return Ok(());
+        };
+
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("fog_upsampling_pass"),
+            color_attachments: &[Some(view_target.get_color_attachment())],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_render_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group.upsampling_bind_group, &[]);
+        render_pass.set_blend_constant(Color::WHITE);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
+
+/// Registers the upsampling pass's render-graph node. Edge wiring (ordering
+/// it after temporal resolve and before bloom) happens once, alongside the
+/// other volumetric fog nodes, in `VolumetricFogPlugin::build`.
+pub fn add_upsampling_graph_node(app: &mut App) {
+    let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+        return;
+    };
+
+    render_app.add_render_graph_node::<ViewNodeRunner<UpsamplingNode>>(
+        Core3d,
+        VolumetricFogUpsamplingLabel,
+    );
+}